@@ -1,23 +1,237 @@
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")]
 
-use std::{process::{Child, Command, Stdio}, thread, time::Duration, sync::{Arc, Mutex}, net::TcpListener, fs};
-use tauri::Manager;
+use std::{path::PathBuf, process::{Child, Command, Stdio}, thread, time::{Duration, Instant, SystemTime, UNIX_EPOCH}, sync::{Arc, Mutex}, fs};
+use serde::Serialize;
+use tauri::{Emitter, Manager};
+
+mod secrets;
+
+// Restart/health-monitoring tuning for the backend supervisor.
+const SUPERVISOR_POLL_INTERVAL: Duration = Duration::from_millis(300);
+// A single missed health check can just be a slow response; only treat the
+// backend as actually down after this many consecutive failures. An
+// unexpected exit, by contrast, is acted on immediately (see
+// `supervise_backend`).
+const SUPERVISOR_HEALTH_FAILURE_THRESHOLD: u32 = 3;
+const SUPERVISOR_INITIAL_BACKOFF: Duration = Duration::from_millis(250);
+const SUPERVISOR_MAX_BACKOFF: Duration = Duration::from_secs(8);
+const SUPERVISOR_MAX_CONSECUTIVE_FAILURES: u32 = 6;
+
+// How long to wait for the backend to exit on its own after asking it to
+// shut down before we give up and kill it.
+const SHUTDOWN_GRACE_PERIOD: Duration = Duration::from_secs(3);
+
+// Bound on health-check / shutdown-request I/O so a wedged backend can't hang
+// the caller (the initial poll in `spawn_backend`, the supervisor's
+// `SUPERVISOR_POLL_INTERVAL` loop, or `graceful_shutdown`'s drain wait). Matches the
+// timeout the old `ureq`-based transport used.
+const HEALTH_IO_TIMEOUT: Duration = Duration::from_millis(100);
 
-#[derive(Clone)]
 struct BackendState {
-    port: u16,
+    endpoint: Mutex<String>,
+    // Bearer token the webview must present on every request; minted once at
+    // startup and never sent anywhere outside this process and its child.
+    token: String,
+}
+
+#[derive(Serialize)]
+struct BackendConnection {
+    url: String,
+    token: String,
 }
 
 #[tauri::command]
-fn get_backend_url(state: tauri::State<BackendState>) -> String {
-    format!("http://127.0.0.1:{}", state.port)
+fn get_backend_url(state: tauri::State<Arc<BackendState>>) -> BackendConnection {
+    let endpoint = state.endpoint.lock().unwrap().clone();
+    #[cfg(unix)]
+    let url = format!("unix://{}", endpoint);
+    #[cfg(windows)]
+    let url = format!("npipe://{}", endpoint);
+    BackendConnection { url, token: state.token.clone() }
+}
+
+#[tauri::command]
+fn get_backend_socket_path(state: tauri::State<Arc<BackendState>>) -> String {
+    state.endpoint.lock().unwrap().clone()
+}
+
+// Short random suffix for the pipe/socket name. Hand-rolled to avoid pulling
+// in a `rand` dependency just for this.
+fn random_id() -> String {
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_nanos())
+        .unwrap_or(0);
+    format!("{:x}{:x}", std::process::id(), nanos)
+}
+
+// Mints a 256-bit per-launch token from the OS CSPRNG. This has to be
+// unguessable by another local process (unlike `random_id`, which only needs
+// to avoid socket-name collisions), so it goes through `getrandom` rather
+// than being hand-rolled from time/pid.
+fn generate_token() -> String {
+    let mut bytes = [0u8; 32];
+    getrandom::getrandom(&mut bytes).expect("failed to read OS random bytes for backend token");
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+#[cfg(unix)]
+fn backend_endpoint_path() -> String {
+    let runtime_dir = std::env::var("XDG_RUNTIME_DIR").unwrap_or_else(|_| std::env::temp_dir().to_string_lossy().into_owned());
+    format!("{}/crm-{}.sock", runtime_dir, random_id())
+}
+
+#[cfg(windows)]
+fn backend_endpoint_path() -> String {
+    format!(r"\\.\pipe\crm-{}", random_id())
+}
+
+// Connects to the backend over the platform transport and issues a minimal
+// hand-rolled HTTP/1.1 GET so we don't need an HTTP client that understands
+// unix sockets / named pipes just for a health check.
+fn check_health(endpoint: &str, token: &str) -> bool {
+    let request = format!(
+        "GET /health HTTP/1.1\r\nHost: localhost\r\nAuthorization: Bearer {}\r\nConnection: close\r\n\r\n",
+        token
+    );
+
+    #[cfg(unix)]
+    {
+        use std::io::{Read, Write};
+
+        let mut stream = match std::os::unix::net::UnixStream::connect(endpoint) {
+            Ok(s) => s,
+            Err(_) => return false,
+        };
+        let _ = stream.set_read_timeout(Some(HEALTH_IO_TIMEOUT));
+        let _ = stream.set_write_timeout(Some(HEALTH_IO_TIMEOUT));
+
+        if stream.write_all(request.as_bytes()).is_err() {
+            return false;
+        }
+
+        let mut buf = [0u8; 64];
+        let n = match stream.read(&mut buf) {
+            Ok(n) => n,
+            Err(_) => return false,
+        };
+        let response = String::from_utf8_lossy(&buf[..n]);
+        return response.starts_with("HTTP/1.1 200") || response.starts_with("HTTP/1.0 200");
+    }
+
+    #[cfg(windows)]
+    {
+        match pipe_roundtrip(endpoint, request, true, HEALTH_IO_TIMEOUT) {
+            Some(response) => response.starts_with("HTTP/1.1 200") || response.starts_with("HTTP/1.0 200"),
+            None => false,
+        }
+    }
 }
 
-fn spawn_backend() -> (Child, u16) {
-    // Reserve a free port and release it so the child can bind to it
-    let listener = TcpListener::bind("127.0.0.1:0").expect("failed to bind port 0");
-    let port = listener.local_addr().unwrap().port();
-    drop(listener);
+// Best-effort request for the backend to shut itself down cleanly, over the
+// same raw-HTTP approach `check_health` uses. Errors are ignored: if this
+// doesn't land, `graceful_shutdown`'s drain timeout falls back to `kill()`.
+fn send_shutdown_request(endpoint: &str, token: &str) {
+    let request = format!(
+        "POST /shutdown HTTP/1.1\r\nHost: localhost\r\nAuthorization: Bearer {}\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+        token
+    );
+
+    #[cfg(unix)]
+    {
+        use std::io::Write;
+
+        if let Ok(stream) = std::os::unix::net::UnixStream::connect(endpoint) {
+            let _ = stream.set_write_timeout(Some(HEALTH_IO_TIMEOUT));
+            let mut stream = stream;
+            let _ = stream.write_all(request.as_bytes());
+        }
+    }
+
+    #[cfg(windows)]
+    {
+        let _ = pipe_roundtrip(endpoint, request, false, HEALTH_IO_TIMEOUT);
+    }
+}
+
+// `std::fs::File` (which is how Windows named pipes are opened) has no
+// timeout API, so the open/write/read has to run on a throwaway thread and
+// be bounded from the calling side with `recv_timeout`. Used by both
+// `check_health` and `send_shutdown_request` to keep a wedged backend from
+// hanging the supervisor or shutdown path.
+#[cfg(windows)]
+fn pipe_roundtrip(endpoint: &str, request: String, read_response: bool, timeout: Duration) -> Option<String> {
+    use std::io::{Read, Write};
+    use std::sync::mpsc;
+
+    let endpoint = endpoint.to_string();
+    let (tx, rx) = mpsc::channel();
+    thread::spawn(move || {
+        let result = (|| -> std::io::Result<String> {
+            let mut stream = fs::OpenOptions::new().read(true).write(true).open(&endpoint)?;
+            stream.write_all(request.as_bytes())?;
+            if !read_response {
+                return Ok(String::new());
+            }
+            let mut buf = [0u8; 64];
+            let n = stream.read(&mut buf)?;
+            Ok(String::from_utf8_lossy(&buf[..n]).into_owned())
+        })();
+        let _ = tx.send(result);
+    });
+
+    rx.recv_timeout(timeout).ok()?.ok()
+}
+
+// Asks the backend to shut down, then waits up to `SHUTDOWN_GRACE_PERIOD` for
+// it to exit on its own before falling back to `kill()`. Avoids SIGKILLing
+// `crm-api` mid-write and corrupting an in-flight SQLite transaction.
+fn graceful_shutdown(child: &mut Child, endpoint: &str, token: &str) {
+    send_shutdown_request(endpoint, token);
+
+    let deadline = Instant::now() + SHUTDOWN_GRACE_PERIOD;
+    while Instant::now() < deadline {
+        if matches!(child.try_wait(), Ok(Some(_))) {
+            return;
+        }
+        thread::sleep(Duration::from_millis(100));
+    }
+
+    let _ = child.kill();
+}
+
+// Debug builds always spawn from the dev tree layout. Release builds either
+// bundle `crm-api` as a sidecar resource (with the `embedded-backend`
+// feature) or keep spawning the dev-tree path, matching whichever backend
+// mode the build was made for.
+fn backend_binary_path(app: &tauri::AppHandle) -> Option<PathBuf> {
+    if cfg!(debug_assertions) {
+        return Some(PathBuf::from("../../backend/bin/crm-api"));
+    }
+
+    #[cfg(feature = "embedded-backend")]
+    {
+        let resource_name = if cfg!(windows) { "backend/crm-api.exe" } else { "backend/crm-api" };
+        app.path()
+            .resolve(resource_name, tauri::path::BaseDirectory::Resource)
+            .ok()
+            .filter(|p| p.exists())
+    }
+
+    #[cfg(not(feature = "embedded-backend"))]
+    {
+        let _ = app;
+        Some(PathBuf::from("../../backend/bin/crm-api"))
+    }
+}
+
+// Returns `None` if the backend binary couldn't be located (e.g. the
+// `embedded-backend` resource is missing) rather than panicking, so a
+// restart attempt from inside `supervise_backend`'s background thread can
+// report `backend-fatal` instead of silently taking down the monitor loop.
+fn spawn_backend(app: &tauri::AppHandle, token: &str) -> Option<(Child, String)> {
+    let binary_path = backend_binary_path(app)?;
+    let endpoint = backend_endpoint_path();
 
     // Load environment variables from project .env for dev runs
     let mut extra_env: Vec<(String, String)> = Vec::new();
@@ -51,12 +265,13 @@ fn spawn_backend() -> (Child, u16) {
         }
     }
 
-    // Start backend with the reserved PORT and .env variables
-    let mut cmd = Command::new("../../backend/bin/crm-api");
-    cmd.env("PORT", port.to_string())
+    // Start backend, handing it the socket/pipe path to bind instead of a TCP port
+    let mut cmd = Command::new(binary_path);
+    cmd.env("CRM_API_ENDPOINT", &endpoint)
+        .env("CRM_API_TOKEN", token)
         .stdout(Stdio::piped())
         .stderr(Stdio::piped());
-    for (k, v) in extra_env {
+    for (k, v) in secrets::resolve(extra_env) {
         cmd.env(k, v);
     }
     let mut child = cmd.spawn().expect("failed to start backend");
@@ -77,44 +292,123 @@ fn spawn_backend() -> (Child, u16) {
     }
 
     // Poll health until ready (timeout ~5s)
-    for _ in 0..100 {
-        if let Ok(resp) = ureq::get(&format!("http://127.0.0.1:{}{}", port, "/health"))
-            .timeout(Duration::from_millis(100))
-            .call() {
-            if resp.status() == 200 { break; }
-        }
+    for _ in 0..50 {
+        if check_health(&endpoint, token) { break; }
         thread::sleep(Duration::from_millis(100));
     }
 
-    (child, port)
+    Some((child, endpoint))
+}
+
+// Watches the backend child on a background thread: reaps it if it exits and
+// periodically re-polls /health, respawning on either an unexpected exit or
+// repeated health failures. Restarts back off exponentially, and the app
+// gives up (emitting a fatal event) after too many failures in a row.
+fn supervise_backend(app: tauri::AppHandle, state: Arc<BackendState>, child_arc: Arc<Mutex<Child>>) {
+    thread::spawn(move || {
+        let mut backoff = SUPERVISOR_INITIAL_BACKOFF;
+        let mut consecutive_failures = 0u32;
+        let mut consecutive_health_failures = 0u32;
+
+        loop {
+            thread::sleep(SUPERVISOR_POLL_INTERVAL);
+
+            let exited = matches!(
+                child_arc.lock().unwrap().try_wait(),
+                Ok(Some(_))
+            );
+            let endpoint = state.endpoint.lock().unwrap().clone();
+
+            if !exited {
+                if check_health(&endpoint, &state.token) {
+                    consecutive_health_failures = 0;
+                    consecutive_failures = 0;
+                    backoff = SUPERVISOR_INITIAL_BACKOFF;
+                    continue;
+                }
+
+                consecutive_health_failures += 1;
+                if consecutive_health_failures < SUPERVISOR_HEALTH_FAILURE_THRESHOLD {
+                    // Don't act on a single bad poll; wait for it to be
+                    // consistently unhealthy.
+                    continue;
+                }
+            }
+            consecutive_health_failures = 0;
+
+            let _ = app.emit("backend-unhealthy", ());
+
+            consecutive_failures += 1;
+            if consecutive_failures > SUPERVISOR_MAX_CONSECUTIVE_FAILURES {
+                let _ = app.emit("backend-fatal", "backend did not recover after repeated restarts");
+                break;
+            }
+
+            thread::sleep(backoff);
+            backoff = (backoff * 2).min(SUPERVISOR_MAX_BACKOFF);
+
+            if !exited {
+                // Still running, just failing health checks: stop it before
+                // starting a replacement so we don't leak the old process
+                // and its still-bound socket/pipe.
+                let mut ch = child_arc.lock().unwrap();
+                graceful_shutdown(&mut ch, &endpoint, &state.token);
+            }
+
+            let Some((new_child, new_endpoint)) = spawn_backend(&app, &state.token) else {
+                let _ = app.emit("backend-fatal", "could not locate backend binary to restart");
+                break;
+            };
+            *child_arc.lock().unwrap() = new_child;
+            *state.endpoint.lock().unwrap() = new_endpoint;
+            let _ = app.emit("backend-restarted", ());
+        }
+    });
 }
 
 fn main() {
     tauri::Builder::default()
         .setup(|app| {
             // Spawn backend and wait
-            let (child, port) = spawn_backend();
-            app.manage(BackendState { port });
-            println!("BACKEND_PORT={}", port);
+            let token = generate_token();
+            let (child, endpoint) = spawn_backend(app.handle(), &token)
+                .expect("could not locate backend binary to launch");
+            println!("BACKEND_ENDPOINT={}", endpoint);
+            let state = Arc::new(BackendState { endpoint: Mutex::new(endpoint), token });
+            app.manage(state.clone());
+            let child_arc = Arc::new(Mutex::new(child));
+            app.manage(child_arc.clone());
             // Show window after short delay (backend health assumed)
             let window = app.get_webview_window("main").unwrap();
             window.show().ok();
 
-            // Ensure backend is killed on app exit via on_window_event on the window
-            let child_arc = Arc::new(Mutex::new(child));
+            supervise_backend(app.handle().clone(), state.clone(), child_arc.clone());
+
+            // Drain the backend on this window closing; RunEvent::ExitRequested
+            // below covers the app quitting by any other path.
             let child_for_close = child_arc.clone();
+            let state_for_close = state.clone();
             window.on_window_event(move |event| {
                 if let tauri::WindowEvent::CloseRequested { .. } = event {
                     if let Ok(mut ch) = child_for_close.lock() {
-                        let _ = ch.kill();
+                        let endpoint = state_for_close.endpoint.lock().unwrap().clone();
+                        graceful_shutdown(&mut ch, &endpoint, &state_for_close.token);
                     }
                 }
             });
             Ok(())
         })
-        .invoke_handler(tauri::generate_handler![get_backend_url])
-        .run(tauri::generate_context!())
-        .expect("error while running tauri application");
+        .invoke_handler(tauri::generate_handler![get_backend_url, get_backend_socket_path, secrets::set_secret])
+        .build(tauri::generate_context!())
+        .expect("error while building tauri application")
+        .run(|app_handle, event| {
+            if let tauri::RunEvent::ExitRequested { .. } = event {
+                let state = app_handle.state::<Arc<BackendState>>();
+                let child_arc = app_handle.state::<Arc<Mutex<Child>>>();
+                if let Ok(mut ch) = child_arc.lock() {
+                    let endpoint = state.endpoint.lock().unwrap().clone();
+                    graceful_shutdown(&mut ch, &endpoint, &state.token);
+                }
+            }
+        });
 }
-
-
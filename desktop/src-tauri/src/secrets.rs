@@ -0,0 +1,157 @@
+// Secrets subsystem: keeps backend credentials (API keys, database URLs) out
+// of plaintext env files at runtime. On first run we import whatever is
+// found in `.env` into the platform secure store (Credential Manager /
+// Keychain / libsecret via the `keyring` crate); after that the store is the
+// source of truth and `.env` is only consulted for keys it doesn't have yet.
+
+use keyring::Entry;
+use std::collections::HashMap;
+
+const SERVICE: &str = "personalcrm";
+
+// Backend credentials we manage through the secure store. This is the set of
+// keys `resolve` looks up regardless of whether `.env` exists, so a release
+// build with secrets already imported never needs `.env` again.
+const SECRET_KEYS: &[&str] = &["OPENAI_API_KEY", "DATABASE_URL"];
+
+// Indirection over the platform keychain so `resolve`'s precedence logic can
+// be table-tested without touching the real OS secure store.
+trait SecretStore {
+    fn get(&self, key: &str) -> Option<String>;
+    fn set(&self, key: &str, value: &str) -> Result<(), String>;
+}
+
+struct KeyringStore;
+
+impl SecretStore for KeyringStore {
+    fn get(&self, key: &str) -> Option<String> {
+        Entry::new(SERVICE, key).ok()?.get_password().ok()
+    }
+
+    fn set(&self, key: &str, value: &str) -> Result<(), String> {
+        Entry::new(SERVICE, key)
+            .map_err(|e| e.to_string())?
+            .set_password(value)
+            .map_err(|e| e.to_string())
+    }
+}
+
+/// Resolves the env vars to hand the backend child. Every key in
+/// `SECRET_KEYS` is looked up in the secure store first; on a miss it falls
+/// back to `.env` and imports the value into the store for next launch. Any
+/// other `.env` entries (non-secret config) pass through unchanged.
+pub fn resolve(dotenv_vars: Vec<(String, String)>) -> Vec<(String, String)> {
+    resolve_with(&KeyringStore, dotenv_vars)
+}
+
+fn resolve_with(
+    store: &impl SecretStore,
+    dotenv_vars: Vec<(String, String)>,
+) -> Vec<(String, String)> {
+    let mut dotenv_vars: HashMap<String, String> = dotenv_vars.into_iter().collect();
+    let mut resolved = Vec::new();
+
+    for key in SECRET_KEYS {
+        let dotenv_value = dotenv_vars.remove(*key);
+        if let Some(stored) = store.get(key) {
+            resolved.push((key.to_string(), stored));
+        } else if let Some(dotenv_value) = dotenv_value {
+            let _ = store.set(key, &dotenv_value);
+            resolved.push((key.to_string(), dotenv_value));
+        }
+    }
+
+    resolved.extend(dotenv_vars);
+    resolved
+}
+
+#[tauri::command]
+pub fn set_secret(name: String, value: String) -> Result<(), String> {
+    KeyringStore.set(&name, &value)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::RefCell;
+
+    struct FakeStore {
+        data: RefCell<HashMap<String, String>>,
+    }
+
+    impl FakeStore {
+        fn new(entries: &[(&str, &str)]) -> Self {
+            FakeStore {
+                data: RefCell::new(
+                    entries
+                        .iter()
+                        .map(|(k, v)| (k.to_string(), v.to_string()))
+                        .collect(),
+                ),
+            }
+        }
+    }
+
+    impl SecretStore for FakeStore {
+        fn get(&self, key: &str) -> Option<String> {
+            self.data.borrow().get(key).cloned()
+        }
+
+        fn set(&self, key: &str, value: &str) -> Result<(), String> {
+            self.data.borrow_mut().insert(key.to_string(), value.to_string());
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn keychain_value_wins_over_dotenv() {
+        let store = FakeStore::new(&[("OPENAI_API_KEY", "from-keychain")]);
+        let dotenv = vec![("OPENAI_API_KEY".to_string(), "from-dotenv".to_string())];
+
+        let resolved = resolve_with(&store, dotenv);
+
+        assert_eq!(
+            resolved,
+            vec![("OPENAI_API_KEY".to_string(), "from-keychain".to_string())]
+        );
+    }
+
+    #[test]
+    fn dotenv_only_value_is_imported_into_store() {
+        let store = FakeStore::new(&[]);
+        let dotenv = vec![("DATABASE_URL".to_string(), "postgres://localhost".to_string())];
+
+        let resolved = resolve_with(&store, dotenv);
+
+        assert_eq!(
+            resolved,
+            vec![("DATABASE_URL".to_string(), "postgres://localhost".to_string())]
+        );
+        assert_eq!(
+            store.get("DATABASE_URL"),
+            Some("postgres://localhost".to_string())
+        );
+    }
+
+    #[test]
+    fn missing_secret_is_simply_omitted() {
+        let store = FakeStore::new(&[]);
+
+        let resolved = resolve_with(&store, Vec::new());
+
+        assert!(resolved.is_empty());
+    }
+
+    #[test]
+    fn non_secret_dotenv_keys_pass_through_unchanged() {
+        let store = FakeStore::new(&[]);
+        let dotenv = vec![("SOME_OTHER_CONFIG".to_string(), "value".to_string())];
+
+        let resolved = resolve_with(&store, dotenv);
+
+        assert_eq!(
+            resolved,
+            vec![("SOME_OTHER_CONFIG".to_string(), "value".to_string())]
+        );
+    }
+}